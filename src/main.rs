@@ -28,6 +28,8 @@ const _IOC_SIZESHIFT: usize = _IOC_TYPESHIFT + _IOC_TYPEBITS;
 const _IOC_DIRSHIFT: usize = _IOC_SIZESHIFT + _IOC_SIZEBITS;
 
 const _IOC_NONE: usize = 0;
+const _IOC_WRITE: usize = 1;
+const _IOC_READ: usize = 2;
 
 macro_rules! _IOC {
 	($dir:expr, $type:expr, $nr:expr, $size:expr) => {
@@ -46,7 +48,24 @@ macro_rules! _IO {
 	};
 }
 
+macro_rules! _IOWR {
+	($type:expr, $nr:expr, $size:expr) => {
+		_IOC!(_IOC_READ | _IOC_WRITE, ($type), ($nr), ($size))
+	};
+}
+
+/* usbfs ioctl wrapper: an interface number plus a nested ioctl code. */
+#[repr(C)]
+struct UsbDevFsIoctl {
+	ifno: libc::c_int,
+	ioctl_code: libc::c_int,
+	data: *mut libc::c_void,
+}
+
 const USBDEVFS_RESET: usize = _IO!(b'U' as usize, 20_usize);
+const USBDEVFS_IOCTL: usize = _IOWR!(b'U' as usize, 18_usize, core::mem::size_of::<UsbDevFsIoctl>());
+const USBDEVFS_DISCONNECT: usize = _IO!(b'U' as usize, 22_usize);
+const USBDEVFS_CONNECT: usize = _IO!(b'U' as usize, 23_usize);
 const USBDEVFS_PATH: &str = "/dev/bus/usb/";
 const USBSYSFS_PATH: &str = "/sys/bus/usb/devices";
 
@@ -56,13 +75,33 @@ const WHITESPACE_CHARS: [char; 3] = [ '\n', '\t', ' ' ];
 #[derive(Debug)]
 enum UsbDeviceIdentifier {
 	BusDev { bus: u16, dev: u16},
-	VendorProduct { vid: u16, pid: u16 },
+	VendorProduct { vid: u16, pid: u16, serial: Option<String> },
+	Serial(String),
 	ProductName(String),
+	/* sysfs port path (e.g. `1-1.4.2`): bus plus the hub-port chain, stable
+	 * across reconnects of the same physical port. */
+	PortPath(String),
 }
 
 struct UsbDevFsEntry {
 	bus: u16,
 	dev: u16,
+	/* sysfs device directory name, i.e. the port path (e.g. `1-1.4.2`);
+	 * empty when the entry was discovered through the procfs backend. */
+	name: String,
+	/* usbfs character device node to open for ioctls. */
+	node: PathBuf,
+}
+
+fn usbfs_node(bus: u16, dev: u16) -> PathBuf {
+	PathBuf::from(format!("{}/{:03}/{:03}", USBDEVFS_PATH, bus, dev))
+}
+
+/* A device-discovery backend. Sysfs is preferred, but on kernels or
+ * containers where only the legacy procfs usbfs is mounted we fall back to
+ * parsing `/proc/bus/usb/devices`. */
+trait DeviceSource {
+	fn find_devices(&self, identifier: &UsbDeviceIdentifier, all: bool) -> Result<Vec<UsbDevFsEntry>>;
 }
 
 fn sysfs_attr_raw<P: AsRef<Path>>(dev: P, attr: &str) -> Result<String> {
@@ -85,49 +124,230 @@ fn sysfs_attr<T: FromStr, P: AsRef<Path>>(dev: P, attr: &str) -> Result<T> {
 		.map_err(|_| ErrorKind::InvalidData.into())
 }
 
-fn find_device(identifier: UsbDeviceIdentifier) -> Result<UsbDevFsEntry> {
-	for entry in std::fs::read_dir(USBSYSFS_PATH)? {
-		let Ok(dir) = entry else { continue; };
-		let dev_name = dir.file_name();
+/* Discovery via the sysfs device tree under `USBSYSFS_PATH`. */
+struct SysfsSource;
+
+impl DeviceSource for SysfsSource {
+	fn find_devices(&self, identifier: &UsbDeviceIdentifier, all: bool) -> Result<Vec<UsbDevFsEntry>> {
+		let mut matches: Vec<UsbDevFsEntry> = Vec::new();
+
+		for entry in std::fs::read_dir(USBSYSFS_PATH)? {
+			let Ok(dir) = entry else { continue; };
+			let dev_name = dir.file_name();
+
+			let Ok(e_bus) = sysfs_attr::<u16, _>(dev_name.as_os_str(), "busnum") else { continue };
+			let Ok(e_dev) = sysfs_attr::<u16, _>(dev_name.as_os_str(), "devnum") else { continue };
+			let e_name = dev_name.to_string_lossy().into_owned();
+
+			match identifier {
+				UsbDeviceIdentifier::BusDev { bus, dev } => {
+					if e_bus == *bus && e_dev == *dev {
+						matches.push(UsbDevFsEntry { bus: e_bus, dev: e_dev, name: e_name.clone(), node: usbfs_node(e_bus, e_dev) });
+						if !all { break; }
+					}
+				},
+				UsbDeviceIdentifier::VendorProduct { vid, pid, serial } => {
+					let Ok(vid_str) = sysfs_attr_raw(&dev_name[..], "idVendor") else { continue };
+					let Ok(pid_str) = sysfs_attr_raw(&dev_name[..], "idProduct") else { continue };
+
+					let cur_vid = u16::from_str_radix(&vid_str[..], 16)
+						.map_err(|_| ErrorKind::InvalidData)?;
+					let cur_pid = u16::from_str_radix(&pid_str[..], 16)
+						.map_err(|_| ErrorKind::InvalidData)?;
+
+					if cur_vid != *vid || cur_pid != *pid {
+						continue;
+					}
+					/* Keep scanning until the serial agrees too, so we don't stop
+					 * on the first vid:pid hit when several identical devices exist. */
+					if let Some(want) = serial {
+						let Ok(cur_serial) = sysfs_attr_raw(dev_name.as_os_str(), "serial") else { continue };
+						if cur_serial != *want {
+							continue;
+						}
+					}
+
+					matches.push(UsbDevFsEntry { bus: e_bus, dev: e_dev, name: e_name.clone(), node: usbfs_node(e_bus, e_dev) });
+					if !all { break; }
+				},
+				UsbDeviceIdentifier::Serial(serial) => {
+					let Ok(cur_serial) = sysfs_attr_raw(dev_name.as_os_str(), "serial") else { continue };
+
+					if cur_serial == *serial {
+						matches.push(UsbDevFsEntry { bus: e_bus, dev: e_dev, name: e_name.clone(), node: usbfs_node(e_bus, e_dev) });
+						if !all { break; }
+					}
+				},
+				UsbDeviceIdentifier::ProductName(name) => {
+					let Ok(cur_name) = sysfs_attr_raw(dev_name.as_os_str(), "product") else { continue };
+
+					if cur_name == *name {
+						matches.push(UsbDevFsEntry { bus: e_bus, dev: e_dev, name: e_name.clone(), node: usbfs_node(e_bus, e_dev) });
+						if !all { break; }
+					}
+				},
+				UsbDeviceIdentifier::PortPath(path) => {
+					/* The directory name is the port path. Interface subdirs
+					 * (containing `:`) are already excluded by the busnum/devnum
+					 * reads above, but bus roots (`usbN`) carry both attributes,
+					 * so skip them here (and guard against `:` anyway). */
+					if e_name.starts_with("usb") || e_name.contains(':') {
+						continue;
+					}
+
+					if e_name == *path {
+						matches.push(UsbDevFsEntry { bus: e_bus, dev: e_dev, name: e_name.clone(), node: usbfs_node(e_bus, e_dev) });
+						if !all { break; }
+					}
+				}
+			}
+		}
+
+		if matches.is_empty() {
+			Err(std::io::Error::from(ErrorKind::NotFound))
+		} else {
+			Ok(matches)
+		}
+	}
+}
 
-		let Ok(e_bus) = sysfs_attr::<u16, _>(dev_name.as_os_str(), "busnum") else { continue };
-		let Ok(e_dev) = sysfs_attr::<u16, _>(dev_name.as_os_str(), "devnum") else { continue };
+const USBPROCFS_PATH: &str = "/proc/bus/usb";
 
-		match identifier {
-			UsbDeviceIdentifier::BusDev { bus, dev } => {
-				if e_bus == bus && e_dev == dev {
-					return Ok(UsbDevFsEntry { bus: e_bus, dev: e_dev });
-				}
-			},
-			UsbDeviceIdentifier::VendorProduct { vid, pid } => {
-				let Ok(vid_str) = sysfs_attr_raw(&dev_name[..], "idVendor") else { continue };
-				let Ok(pid_str) = sysfs_attr_raw(&dev_name[..], "idProduct") else { continue };
-
-				let cur_vid = u16::from_str_radix(&vid_str[..], 16)
-					.map_err(|_| ErrorKind::InvalidData)?;
-				let cur_pid = u16::from_str_radix(&pid_str[..], 16)
-					.map_err(|_| ErrorKind::InvalidData)?;
-
-				if cur_vid == vid && cur_pid == pid {
-					return Ok(UsbDevFsEntry { bus: e_bus, dev: e_dev });
-				}
-			},
-			UsbDeviceIdentifier::ProductName(ref name) => {
-				let Ok(cur_name) = sysfs_attr_raw(dev_name.as_os_str(), "product") else { continue };
+/* Discovery by parsing the legacy `/proc/bus/usb/devices` text dump, for
+ * kernels/containers that expose procfs usbfs but no sysfs. The node lives
+ * under `/proc/bus/usb/BBB/DDD` there instead of `/dev/bus/usb`. */
+struct ProcfsSource;
 
-				if cur_name == *name {
-					return Ok(UsbDevFsEntry { bus: e_bus, dev: e_dev });
-				}
+impl ProcfsSource {
+	fn node(bus: u16, dev: u16) -> PathBuf {
+		PathBuf::from(format!("{}/{:03}/{:03}", USBPROCFS_PATH, bus, dev))
+	}
+}
+
+impl DeviceSource for ProcfsSource {
+	fn find_devices(&self, identifier: &UsbDeviceIdentifier, all: bool) -> Result<Vec<UsbDevFsEntry>> {
+		/* The procfs dump carries no topology path, so port-path matching can
+		 * never succeed here; fail loudly instead of returning a misleading
+		 * `NotFound` that looks like the device is simply absent. */
+		if matches!(identifier, UsbDeviceIdentifier::PortPath(_)) {
+			println!("Port-path matching is only supported by the sysfs backend");
+			return Err(std::io::Error::from(ErrorKind::InvalidInput));
+		}
+
+		let contents = fs::read_to_string(format!("{USBPROCFS_PATH}/devices"))?;
+
+		let mut matches: Vec<UsbDevFsEntry> = Vec::new();
+
+		/* Per-device accumulator, reset on each `T:` topology line. */
+		let (mut bus, mut dev) = (0u16, 0u16);
+		let (mut vid, mut pid) = (0u16, 0u16);
+		let mut serial: Option<String> = None;
+		let mut product: Option<String> = None;
+		let mut have_dev = false;
+
+		let consider = |bus: u16, dev: u16, vid: u16, pid: u16,
+			serial: &Option<String>, product: &Option<String>,
+			matches: &mut Vec<UsbDevFsEntry>| -> bool
+		{
+			let hit = match identifier {
+				UsbDeviceIdentifier::BusDev { bus: b, dev: d } => bus == *b && dev == *d,
+				UsbDeviceIdentifier::VendorProduct { vid: v, pid: p, serial: s } => {
+					vid == *v && pid == *p && match s {
+						Some(want) => serial.as_deref() == Some(want.as_str()),
+						None => true,
+					}
+				},
+				UsbDeviceIdentifier::Serial(s) => serial.as_deref() == Some(s.as_str()),
+				UsbDeviceIdentifier::ProductName(n) => product.as_deref() == Some(n.as_str()),
+				/* procfs carries no topology path; port-path matching is sysfs-only. */
+				UsbDeviceIdentifier::PortPath(_) => false,
+			};
+			if hit {
+				matches.push(UsbDevFsEntry {
+					bus, dev, name: String::new(), node: ProcfsSource::node(bus, dev),
+				});
 			}
+			hit && !all
+		};
+
+		for line in contents.lines() {
+			let line = line.trim_end_matches(WHITESPACE_CHARS.as_slice());
+			let Some((tag, rest)) = line.split_once(':') else { continue };
+
+			match tag {
+				"T" => {
+					/* Flush the previous record before starting a new one. */
+					if have_dev && consider(bus, dev, vid, pid, &serial, &product, &mut matches) {
+						return Ok(matches);
+					}
+					bus = proc_field(rest, "Bus").and_then(|v| v.parse().ok()).unwrap_or(0);
+					dev = proc_field(rest, "Dev#").and_then(|v| v.parse().ok()).unwrap_or(0);
+					(vid, pid) = (0, 0);
+					serial = None;
+					product = None;
+					have_dev = true;
+				},
+				"P" => {
+					vid = proc_field(rest, "Vendor").and_then(|v| u16::from_str_radix(&v, 16).ok()).unwrap_or(0);
+					pid = proc_field(rest, "ProdID").and_then(|v| u16::from_str_radix(&v, 16).ok()).unwrap_or(0);
+				},
+				"S" => {
+					if let Some(v) = rest.trim_start().strip_prefix("SerialNumber=") {
+						serial = Some(v.trim().to_string());
+					} else if let Some(v) = rest.trim_start().strip_prefix("Product=") {
+						product = Some(v.trim().to_string());
+					}
+				},
+				_ => {}
+			}
+		}
+
+		if have_dev {
+			consider(bus, dev, vid, pid, &serial, &product, &mut matches);
+		}
+
+		if matches.is_empty() {
+			Err(std::io::Error::from(ErrorKind::NotFound))
+		} else {
+			Ok(matches)
 		}
 	}
+}
+
+/* Pull the value following `Key=` out of a procfs line. The kernel pads some
+ * fields (e.g. `Dev#=  1`), so skip any whitespace before the value token. */
+fn proc_field(line: &str, key: &str) -> Option<String> {
+	let needle = format!("{key}=");
+	let idx = line.find(&needle)?;
+	let rest = line[idx + needle.len()..].trim_start();
+	rest.split_whitespace().next().map(|v| v.to_string())
+}
 
-	Err(std::io::Error::from(ErrorKind::NotFound))
+/* Pick the discovery backend, honouring an explicit `--source` override and
+ * otherwise auto-selecting whichever of sysfs/procfs is mounted. */
+fn select_source(forced: Option<&str>) -> Result<Box<dyn DeviceSource>> {
+	match forced {
+		Some("sysfs") => Ok(Box::new(SysfsSource)),
+		Some("procfs") => Ok(Box::new(ProcfsSource)),
+		Some(other) => {
+			println!("Unknown source '{other}', expected 'sysfs' or 'procfs'");
+			Err(std::io::Error::from(ErrorKind::InvalidInput))
+		},
+		None => {
+			if Path::new(USBSYSFS_PATH).is_dir() {
+				Ok(Box::new(SysfsSource))
+			} else if Path::new(&format!("{USBPROCFS_PATH}/devices")).exists() {
+				Ok(Box::new(ProcfsSource))
+			} else {
+				println!("Neither sysfs nor procfs usbfs is available");
+				Err(std::io::Error::from(ErrorKind::NotFound))
+			}
+		}
+	}
 }
 
 fn reset_device(usbdev: UsbDevFsEntry) -> Result<()> {
-	let path = format!("{}/{:03}/{:03}", USBDEVFS_PATH, usbdev.bus, usbdev.dev);
-	let dev_file = fs::OpenOptions::new().write(true).open(path)?;
+	let dev_file = fs::OpenOptions::new().write(true).open(&usbdev.node)?;
 
 	#[cfg(target_env = "musl")]
 	let res = unsafe {
@@ -147,10 +367,147 @@ fn reset_device(usbdev: UsbDevFsEntry) -> Result<()> {
 	}
 }
 
+/* Enumerate a device's interface numbers from the `bInterfaceNumber` files
+ * under its `bus-port:config.intf` sysfs subdirectories. */
+fn device_interfaces(name: &str) -> Result<Vec<libc::c_int>> {
+	let mut dev_dir = PathBuf::from_str(USBSYSFS_PATH).unwrap();
+	dev_dir.push(name);
+
+	let mut ifnos = Vec::new();
+	for entry in fs::read_dir(dev_dir)? {
+		let Ok(dir) = entry else { continue };
+		let sub = dir.file_name();
+		let Some(sub) = sub.to_str() else { continue };
+
+		/* Interface subdirs carry the `:config.intf` suffix. */
+		if !sub.contains(':') {
+			continue;
+		}
+
+		let rel = format!("{name}/{sub}");
+		let Ok(ifno) = sysfs_attr::<libc::c_int, _>(rel.as_str(), "bInterfaceNumber") else { continue };
+		ifnos.push(ifno);
+	}
+
+	ifnos.sort_unstable();
+	Ok(ifnos)
+}
+
+/* Soft reset: detach and reattach the kernel driver per interface instead of
+ * issuing a full port reset, avoiding the enumeration storm a reset causes. */
+fn rebind_device(usbdev: UsbDevFsEntry) -> Result<()> {
+	/* Rebind needs the interface topology, which only the sysfs backend
+	 * provides; a procfs-discovered entry has an empty `name` and would make
+	 * `device_interfaces` walk the whole device root. */
+	if usbdev.name.is_empty() {
+		println!("USB rebind requires the sysfs backend (--source=sysfs)");
+		return Err(std::io::Error::from(ErrorKind::InvalidInput));
+	}
+
+	let dev_file = fs::OpenOptions::new().write(true).open(&usbdev.node)?;
+
+	let ifnos = device_interfaces(&usbdev.name)?;
+	if ifnos.is_empty() {
+		println!("USB rebind failed: no interfaces found");
+		return Err(std::io::Error::from(ErrorKind::NotFound));
+	}
+
+	for ifno in ifnos {
+		for code in [USBDEVFS_DISCONNECT, USBDEVFS_CONNECT] {
+			let mut cmd = UsbDevFsIoctl {
+				ifno,
+				ioctl_code: code as libc::c_int,
+				data: std::ptr::null_mut(),
+			};
+
+			#[cfg(target_env = "musl")]
+			let res = unsafe {
+				libc::ioctl(dev_file.as_raw_fd() as libc::c_int, USBDEVFS_IOCTL as libc::c_int, &mut cmd)
+			};
+			#[cfg(not(target_env = "musl"))]
+			let res = unsafe {
+				libc::ioctl(dev_file.as_raw_fd() as libc::c_int, USBDEVFS_IOCTL as libc::c_ulong, &mut cmd)
+			};
+
+			if res != 0 {
+				println!("USB rebind failed on interface {ifno}: {res}");
+				return Err(std::io::Error::from(std::io::ErrorKind::Other));
+			}
+		}
+	}
+
+	println!("USB rebind successful");
+	Ok(())
+}
+
+/* Enumerate every device under `USBSYSFS_PATH` and print a stable identity
+ * line per device, modeled on udev's `usb_id`: bus/dev, idVendor:idProduct,
+ * manufacturer, product, serial and the port path. When a device exposes no
+ * `serial`, synthesize one from its vid/pid the way `usb_id` falls back. */
+fn list_devices() -> Result<()> {
+	for entry in fs::read_dir(USBSYSFS_PATH)? {
+		let Ok(dir) = entry else { continue };
+		let dev_name = dir.file_name();
+		let name = dev_name.to_string_lossy();
+
+		/* Skip bus roots (`usbN`) and interface subdirs (those with `:`). */
+		if name.starts_with("usb") || name.contains(':') {
+			continue;
+		}
+
+		let Ok(bus) = sysfs_attr::<u16, _>(dev_name.as_os_str(), "busnum") else { continue };
+		let Ok(dev) = sysfs_attr::<u16, _>(dev_name.as_os_str(), "devnum") else { continue };
+
+		let vid = sysfs_attr_raw(dev_name.as_os_str(), "idVendor").unwrap_or_default();
+		let pid = sysfs_attr_raw(dev_name.as_os_str(), "idProduct").unwrap_or_default();
+		let manufacturer = sysfs_attr_raw(dev_name.as_os_str(), "manufacturer").unwrap_or_default();
+		let product = sysfs_attr_raw(dev_name.as_os_str(), "product").unwrap_or_default();
+		let serial = sysfs_attr_raw(dev_name.as_os_str(), "serial")
+			.unwrap_or_else(|_| format!("{vid}_{pid}"));
+
+		println!("{bus:03}/{dev:03} {vid}:{pid} {manufacturer} | {product} | {serial} | {name}");
+	}
+
+	Ok(())
+}
+
 fn main() -> Result<()> {
 	println!("USB DEVICE RESET");
 
-	let args = env::args().skip(1).collect::<Vec<String>>();
+	let all_args = env::args().skip(1).collect::<Vec<String>>();
+
+	/* Pull `--source` out first so the space-separated `--source sysfs` form
+	 * consumes its value before the flag/positional partition runs. */
+	let mut source: Option<String> = None;
+	let mut rest: Vec<String> = Vec::new();
+	let mut it = all_args.into_iter();
+	while let Some(arg) = it.next() {
+		if let Some(val) = arg.strip_prefix("--source=") {
+			source = Some(val.to_string());
+		} else if arg == "--source" {
+			match it.next() {
+				Some(val) => source = Some(val),
+				None => {
+					println!("--source requires a value (sysfs or procfs)");
+					return Err(std::io::Error::from(ErrorKind::InvalidInput));
+				}
+			}
+		} else {
+			rest.push(arg);
+		}
+	}
+
+	let (flags, args): (Vec<String>, Vec<String>) = rest
+		.into_iter()
+		.partition(|a| a.starts_with("--"));
+
+	let rebind = flags.iter().any(|f| f == "--rebind");
+	let all = flags.iter().any(|f| f == "--all");
+
+	if flags.iter().any(|f| f == "--list" || f == "--identify") {
+		return list_devices();
+	}
+
 	if args.len() < 1 {
 		println!("No usb device specified!");
 		return Err(std::io::Error::from(ErrorKind::InvalidInput).into());
@@ -160,22 +517,51 @@ fn main() -> Result<()> {
 	
 	let (mut bus, mut dev) = (0, 0);
 	let (mut vid_str, mut pid_str) = (String::new(), String::new());
+	let mut serial_str = String::new();
 
 	if scanf::sscanf!(&args[0], "{u16}/{u16}", bus, dev).is_ok() {
 		identifier = UsbDeviceIdentifier::BusDev { bus, dev }
+	} else if scanf::sscanf!(&args[0], "serial={string}", serial_str).is_ok() {
+		identifier = UsbDeviceIdentifier::Serial(serial_str);
+	} else if let Some(port) = args[0].strip_prefix("port=") {
+		identifier = UsbDeviceIdentifier::PortPath(port.to_string());
 	} else if scanf::sscanf!(&args[0], "{string}:{string}", vid_str, pid_str).is_ok() {
 		let vid = u16::from_str_radix(&vid_str[..], 16)
 			.map_err(|_| ErrorKind::InvalidData)?;
 		let pid = u16::from_str_radix(&pid_str[..], 16)
 			.map_err(|_| ErrorKind::InvalidData)?;
 
-		identifier = UsbDeviceIdentifier::VendorProduct { vid, pid };
+		/* An optional second argument `serial=...` turns this into a composite match. */
+		let serial = args.get(1)
+			.and_then(|a| a.strip_prefix("serial="))
+			.map(|s| s.to_string());
+
+		identifier = UsbDeviceIdentifier::VendorProduct { vid, pid, serial };
 	} else {
 		let mut name = String::new();
 		scanf::sscanf!(args[0].as_str(), "{string}", name)?;
 		identifier = UsbDeviceIdentifier::ProductName(name);
 	}
 
-	let usbdev = find_device(identifier)?;
-	Ok(reset_device(usbdev)?)
+	let backend = select_source(source.as_deref())?;
+	let devices = backend.find_devices(&identifier, all)?;
+
+	let mut failed = 0;
+	for usbdev in devices {
+		println!("-> {} ({:03}/{:03})", usbdev.name, usbdev.bus, usbdev.dev);
+		let res = if rebind {
+			rebind_device(usbdev)
+		} else {
+			reset_device(usbdev)
+		};
+		if res.is_err() {
+			failed += 1;
+		}
+	}
+
+	if failed > 0 {
+		Err(std::io::Error::from(std::io::ErrorKind::Other))
+	} else {
+		Ok(())
+	}
 }
\ No newline at end of file